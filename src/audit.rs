@@ -0,0 +1,114 @@
+use std::{fs::OpenOptions, io::Write};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+/// One structured, replayable record of something that happened to a
+/// variation (or a batch) during a pricing run, so a run's changes can be
+/// reconciled against Square or rolled back later.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    PriceChanged {
+        variation_id: String,
+        item_id: String,
+        name: String,
+        old_retail: Decimal,
+        new_retail: Decimal,
+        old_por: Decimal,
+        new_por: Decimal,
+        unit_cost: Decimal,
+        tax_rate: Decimal,
+        rule: String,
+        at: DateTime<Utc>,
+    },
+    Skipped {
+        variation_id: String,
+        item_id: String,
+        name: String,
+        reason: String,
+        at: DateTime<Utc>,
+    },
+    CostSynced {
+        variation_id: String,
+        item_id: String,
+        name: String,
+        sku: Option<String>,
+        upc: Option<String>,
+        old_cost: Decimal,
+        new_cost: Decimal,
+        at: DateTime<Utc>,
+    },
+    BatchUpserted {
+        idempotency_key: String,
+        variation_count: usize,
+        error: Option<String>,
+        at: DateTime<Utc>,
+    },
+}
+
+/// A destination for audit events. Implementations should be append-only so
+/// a sink can later be swapped for a streaming backend without losing
+/// history.
+pub trait Sink {
+    fn record(&mut self, event: &Event) -> Result<()>;
+}
+
+/// Appends one newline-delimited JSON object per event to a file.
+pub struct FileSink {
+    file: std::fs::File,
+}
+
+impl FileSink {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("opening audit log at {path}"))?;
+        Ok(Self { file })
+    }
+}
+
+impl Sink for FileSink {
+    fn record(&mut self, event: &Event) -> Result<()> {
+        writeln!(self.file, "{}", serde_json::to_string(event)?)?;
+        Ok(())
+    }
+}
+
+/// Prints one JSON object per event to stdout.
+pub struct StdoutSink;
+
+impl Sink for StdoutSink {
+    fn record(&mut self, event: &Event) -> Result<()> {
+        println!("{}", serde_json::to_string(event)?);
+        Ok(())
+    }
+}
+
+/// Fans every recorded event out to whichever sinks are configured
+/// (a `--audit-log` file, stdout, both, or neither).
+pub struct AuditLog(Vec<Box<dyn Sink>>);
+
+impl AuditLog {
+    pub fn new(path: Option<&str>, stdout: bool) -> Result<Self> {
+        let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+        if let Some(path) = path {
+            sinks.push(Box::new(FileSink::open(path)?));
+        }
+        if stdout {
+            sinks.push(Box::new(StdoutSink));
+        }
+        Ok(Self(sinks))
+    }
+
+    pub fn record(&mut self, event: Event) -> Result<()> {
+        for sink in &mut self.0 {
+            sink.record(&event)?;
+        }
+        Ok(())
+    }
+}
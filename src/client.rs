@@ -0,0 +1,240 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use http::{
+    HeaderMap, HeaderValue, Method, StatusCode,
+    header::{CONTENT_TYPE, RETRY_AFTER},
+};
+use reqwest::Client;
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
+use reqwest_retry::{
+    DefaultRetryableStrategy, RetryTransientMiddleware, Retryable, RetryableStrategy,
+    policies::ExponentialBackoff,
+};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use tokio::sync::Mutex;
+
+const BASE_URL: &str = "https://connect.squareup.com";
+
+/// Number of times we'll re-authenticate or back off for 401/429 before
+/// giving up and surfacing the error to the caller.
+const MAX_RETRIES: u32 = 8;
+
+/// A typed Square API client that owns its own access/refresh tokens and
+/// app credentials, transparently refreshing an expired access token on a
+/// 401 (mirroring how a well-behaved brokerage API client re-auths before
+/// retrying the failed request) and honoring `Retry-After` on a 429 rather
+/// than burning the fixed exponential-backoff budget on it.
+#[derive(Clone)]
+pub struct SquareClient {
+    http: ClientWithMiddleware,
+    tokens: Arc<Mutex<Tokens>>,
+    app_id: String,
+    app_secret: String,
+}
+
+struct Tokens {
+    access_token: String,
+    refresh_token: String,
+}
+
+/// Retries what the default strategy treats as transient, except 429s, which
+/// are passed through to `send` so it can honor `Retry-After`.
+struct TransientExceptRateLimit;
+
+impl RetryableStrategy for TransientExceptRateLimit {
+    fn handle(&self, res: &reqwest_middleware::Result<reqwest::Response>) -> Option<Retryable> {
+        match res {
+            Ok(res) if res.status() == StatusCode::TOO_MANY_REQUESTS => None,
+            _ => DefaultRetryableStrategy.handle(res),
+        }
+    }
+}
+
+impl SquareClient {
+    pub fn new(
+        app_id: impl Into<String>,
+        app_secret: impl Into<String>,
+        access_token: impl Into<String>,
+        refresh_token: impl Into<String>,
+    ) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert("Square-Version", HeaderValue::from_static("2025-10-16"));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+        let http = Client::builder()
+            .timeout(Duration::from_secs(30))
+            .default_headers(headers)
+            .build()
+            .unwrap();
+
+        let retry_policy = ExponentialBackoff::builder().build_with_max_retries(3);
+        let http = ClientBuilder::new(http)
+            .with(RetryTransientMiddleware::new_with_policy_and_strategy(
+                retry_policy,
+                TransientExceptRateLimit,
+            ))
+            .build();
+
+        Self {
+            http,
+            tokens: Arc::new(Mutex::new(Tokens {
+                access_token: access_token.into(),
+                refresh_token: refresh_token.into(),
+            })),
+            app_id: app_id.into(),
+            app_secret: app_secret.into(),
+        }
+    }
+
+    /// Lists every catalog object of `types`, following `cursor` pages until
+    /// exhausted.
+    pub async fn list_catalog<T: DeserializeOwned>(&self, types: &str) -> Result<Vec<T>> {
+        let mut cursor = Some(String::new());
+        let mut result = Vec::new();
+
+        while let Some(current) = cursor.as_deref() {
+            let mut query = vec![("types", types)];
+            if !current.is_empty() {
+                query.push(("cursor", current));
+            }
+
+            let mut data = self
+                .send(Method::GET, "/v2/catalog/list", &query, None::<&()>)
+                .await?;
+            cursor = data.get("cursor").and_then(|c| c.as_str().map(Into::into));
+            result.extend(match data.get_mut("objects") {
+                Some(value) => serde_json::from_value(value.take())?,
+                None => Vec::new(),
+            });
+        }
+
+        Ok(result)
+    }
+
+    /// Retrieves the catalog objects for `object_ids` in one request.
+    pub async fn batch_retrieve<T: DeserializeOwned>(&self, object_ids: &[String]) -> Result<Vec<T>> {
+        let mut data = self
+            .send(
+                Method::POST,
+                "/v2/catalog/batch-retrieve",
+                &[],
+                Some(&json!({
+                    "object_ids": object_ids,
+                    "include_category_path_to_root": false,
+                    "include_related_objects": false,
+                })),
+            )
+            .await?;
+
+        Ok(match data.get_mut("objects") {
+            Some(value) => serde_json::from_value(value.take())?,
+            None => Vec::new(),
+        })
+    }
+
+    /// Upserts `objects` as a single batch under `idempotency_key`.
+    pub async fn batch_upsert<T: Serialize>(
+        &self,
+        idempotency_key: &str,
+        objects: &[T],
+    ) -> Result<()> {
+        self.send(
+            Method::POST,
+            "/v2/catalog/batch-upsert",
+            &[],
+            Some(&json!({
+                "idempotency_key": idempotency_key,
+                "batches": [{ "objects": objects }],
+            })),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Low-level typed POST, for endpoints that don't yet have a dedicated
+    /// helper above.
+    pub async fn post<T: DeserializeOwned>(&self, path: &str, body: &impl Serialize) -> Result<T> {
+        let data = self.send(Method::POST, path, &[], Some(body)).await?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, &str)],
+        body: Option<&impl Serialize>,
+    ) -> Result<Value> {
+        let url = format!("{BASE_URL}{path}");
+
+        for attempt in 0..=MAX_RETRIES {
+            let access_token = self.tokens.lock().await.access_token.clone();
+            let mut req = self
+                .http
+                .request(method.clone(), &url)
+                .query(query)
+                .bearer_auth(access_token);
+            if let Some(body) = body {
+                req = req.json(body);
+            }
+
+            let res = req.send().await?;
+
+            if res.status() == StatusCode::UNAUTHORIZED && attempt < MAX_RETRIES {
+                self.refresh_token().await?;
+                continue;
+            }
+
+            if res.status() == StatusCode::TOO_MANY_REQUESTS && attempt < MAX_RETRIES {
+                let wait = res
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .unwrap_or(1);
+                tokio::time::sleep(Duration::from_secs(wait)).await;
+                continue;
+            }
+
+            return Ok(res.error_for_status()?.json().await?);
+        }
+
+        unreachable!("loop always returns or errors within MAX_RETRIES retries")
+    }
+
+    /// Performs the OAuth `obtain-token` refresh and swaps in the new
+    /// access (and, if rotated, refresh) token.
+    async fn refresh_token(&self) -> Result<()> {
+        let mut tokens = self.tokens.lock().await;
+
+        let res: Value = self
+            .http
+            .post(format!("{BASE_URL}/oauth2/token"))
+            .json(&json!({
+                "client_id": self.app_id,
+                "client_secret": self.app_secret,
+                "grant_type": "refresh_token",
+                "refresh_token": tokens.refresh_token,
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        tokens.access_token = res
+            .get("access_token")
+            .and_then(|v| v.as_str())
+            .context("refresh response missing access_token")?
+            .to_string();
+
+        if let Some(refresh_token) = res.get("refresh_token").and_then(|v| v.as_str()) {
+            tokens.refresh_token = refresh_token.to_string();
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use rust_decimal::{Decimal, dec};
+use serde::Deserialize;
+
+use crate::guardrail::GuardrailConfig;
+
+/// Pricing rules read from a TOML config file: which VAT rate applies to
+/// each Square tax ID, what margin (POR) each tax bracket should target,
+/// how a rounded retail price gets snapped to a charm ending, and the
+/// per-variation velocity targets `AdjustByVelocity` nudges toward.
+///
+/// This replaces the hard-coded tax-ID match and `0.4` POR constant so a
+/// store with different VAT classes and margin policy can run this tool
+/// unmodified.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    #[serde(rename = "tax")]
+    pub tax_rates: HashMap<String, Decimal>,
+    #[serde(default)]
+    pub margin: MarginConfig,
+    #[serde(default = "default_rounding_bands")]
+    pub rounding: Vec<RoundingBand>,
+    #[serde(default)]
+    pub velocity: VelocityConfig,
+    #[serde(default)]
+    pub guardrail: GuardrailConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MarginConfig {
+    #[serde(default = "default_por")]
+    pub default_por: Decimal,
+    /// Overrides `default_por` for variations taxed at a given VAT rate,
+    /// keyed by that rate (e.g. `"0.2"`).
+    #[serde(default)]
+    pub by_tax_rate: HashMap<String, Decimal>,
+}
+
+impl Default for MarginConfig {
+    fn default() -> Self {
+        Self {
+            default_por: default_por(),
+            by_tax_rate: HashMap::new(),
+        }
+    }
+}
+
+fn default_por() -> Decimal {
+    dec!(0.4)
+}
+
+/// A retail-rounding band: a variation's pennies are snapped to `ending`
+/// once its last digit is `<= upto`. Bands must be supplied in ascending
+/// `upto` order and the last one should cover digit `9`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RoundingBand {
+    pub upto: u8,
+    pub ending: u8,
+}
+
+fn default_rounding_bands() -> Vec<RoundingBand> {
+    vec![
+        RoundingBand { upto: 2, ending: 0 },
+        RoundingBand { upto: 5, ending: 5 },
+        RoundingBand { upto: 9, ending: 9 },
+    ]
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VelocityConfig {
+    #[serde(default = "default_velocity_denominator")]
+    pub denominator: Decimal,
+    #[serde(default)]
+    pub variations: HashMap<String, VelocityTarget>,
+}
+
+impl Default for VelocityConfig {
+    fn default() -> Self {
+        Self {
+            denominator: default_velocity_denominator(),
+            variations: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VelocityTarget {
+    /// Expected unit sales over the trailing window.
+    pub target: Decimal,
+    /// POR floor an adjusted retail is clamped to before it is snapped to a
+    /// rounding band; that snap can still move it a few pence below.
+    #[serde(default)]
+    pub min_por: Decimal,
+    /// POR ceiling an adjusted retail is clamped to before it is snapped to a
+    /// rounding band; that snap can still move it a few pence above.
+    #[serde(default = "default_max_por")]
+    pub max_por: Decimal,
+}
+
+fn default_velocity_denominator() -> Decimal {
+    dec!(8)
+}
+
+fn default_max_por() -> Decimal {
+    dec!(0.6)
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("reading config at {path}"))?;
+        toml::from_str(&raw).with_context(|| format!("parsing config at {path}"))
+    }
+
+    /// Looks up the VAT rate for a Square tax ID. Unknown tax IDs are logged
+    /// and skipped rather than panicking the whole run.
+    pub fn vat_rate(&self, tax_id: &str) -> Option<Decimal> {
+        match self.tax_rates.get(tax_id) {
+            Some(rate) => Some(*rate),
+            None => {
+                eprintln!("unknown tax id {tax_id}, skipping variation");
+                None
+            }
+        }
+    }
+
+    /// The target POR for a variation taxed at `tax_rate`, falling back to
+    /// the configured default margin.
+    ///
+    /// Matches by parsed, scale-normalized value rather than a literal string
+    /// comparison, so a `by_tax_rate` key written as `"0.2"` still matches a
+    /// TOML tax rate written `0.20`.
+    pub fn target_por(&self, tax_rate: Decimal) -> Decimal {
+        let tax_rate = tax_rate.normalize();
+        self.margin
+            .by_tax_rate
+            .iter()
+            .find(|(key, _)| {
+                key.parse::<Decimal>()
+                    .is_ok_and(|rate| rate.normalize() == tax_rate)
+            })
+            .map(|(_, por)| *por)
+            .unwrap_or(self.margin.default_por)
+    }
+}
@@ -0,0 +1,63 @@
+use std::fs::File;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+/// One row of observed unit cost for a SKU or UPC, as read from an external
+/// cost feed.
+#[derive(Debug, Clone)]
+pub struct CostFeedRow {
+    pub sku: Option<String>,
+    pub upc: Option<String>,
+    pub unit_cost: Decimal,
+}
+
+/// A source of fresh unit costs, keyed by SKU/UPC. `SyncCosts` always reads
+/// its rows from a [`CsvCostFeed`]; another source, such as a live supplier
+/// API, can implement this same trait.
+#[async_trait]
+pub trait CostFeedSource {
+    async fn rows(&self) -> Result<Vec<CostFeedRow>>;
+}
+
+#[derive(Debug, Deserialize)]
+struct CsvRow {
+    sku: Option<String>,
+    upc: Option<String>,
+    unit_cost: Decimal,
+}
+
+/// Reads `sku,upc,unit_cost` rows from a CSV file on disk.
+pub struct CsvCostFeed {
+    path: String,
+}
+
+impl CsvCostFeed {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl CostFeedSource for CsvCostFeed {
+    async fn rows(&self) -> Result<Vec<CostFeedRow>> {
+        let file =
+            File::open(&self.path).with_context(|| format!("opening cost feed at {}", self.path))?;
+        let mut reader = csv::Reader::from_reader(file);
+
+        let mut rows = Vec::new();
+        for record in reader.deserialize() {
+            let record: CsvRow =
+                record.with_context(|| format!("reading row from {}", self.path))?;
+            rows.push(CostFeedRow {
+                sku: record.sku.filter(|s| !s.is_empty()),
+                upc: record.upc.filter(|s| !s.is_empty()),
+                unit_cost: record.unit_cost,
+            });
+        }
+
+        Ok(rows)
+    }
+}
@@ -0,0 +1,125 @@
+use rust_decimal::{Decimal, dec};
+use serde::Deserialize;
+
+/// Stop-loss-style limits applied to computed price changes before they're
+/// upserted, so a bad cost import or a tax-rate mislabel can't silently mark
+/// the whole catalog below cost.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GuardrailConfig {
+    /// Largest fractional increase (e.g. `0.25` for 25%) a single run may
+    /// apply to a variation's retail price.
+    #[serde(default = "default_max_increase_pct")]
+    pub max_increase_pct: Decimal,
+    /// POR above which a price change is considered implausible rather than
+    /// a genuine margin improvement.
+    #[serde(default = "default_max_por")]
+    pub max_por: Decimal,
+}
+
+impl Default for GuardrailConfig {
+    fn default() -> Self {
+        Self {
+            max_increase_pct: default_max_increase_pct(),
+            max_por: default_max_por(),
+        }
+    }
+}
+
+fn default_max_increase_pct() -> Decimal {
+    dec!(0.25)
+}
+
+fn default_max_por() -> Decimal {
+    dec!(0.75)
+}
+
+/// A single price change rejected by [`check_price_change`].
+#[derive(Debug, Clone)]
+pub struct GuardrailViolation {
+    pub variation_id: String,
+    pub item_id: String,
+    pub name: String,
+    pub reason: String,
+}
+
+/// Checks one computed price change against `config`'s limits, returning the
+/// first violation found, if any.
+pub fn check_price_change(
+    variation_id: &str,
+    item_id: &str,
+    name: &str,
+    old_retail: Decimal,
+    new_retail: Decimal,
+    new_por: Decimal,
+    config: &GuardrailConfig,
+) -> Option<GuardrailViolation> {
+    let violation = |reason: String| {
+        Some(GuardrailViolation {
+            variation_id: variation_id.to_string(),
+            item_id: item_id.to_string(),
+            name: name.to_string(),
+            reason,
+        })
+    };
+
+    if new_por < dec!(0) {
+        return violation(format!(
+            "new POR {new_por} is negative, meaning the net-of-VAT price would fall below unit cost"
+        ));
+    }
+
+    if old_retail > dec!(0) {
+        let increase = (new_retail - old_retail) / old_retail;
+        if increase > config.max_increase_pct {
+            return violation(format!(
+                "retail would increase by {:.1}%, exceeding the {:.1}% guardrail",
+                increase * dec!(100),
+                config.max_increase_pct * dec!(100)
+            ));
+        }
+    }
+
+    if new_por > config.max_por {
+        return violation(format!(
+            "implied POR {new_por} exceeds the implausible ceiling {}",
+            config.max_por
+        ));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows_a_plausible_margin_improvement() {
+        let config = GuardrailConfig::default();
+        assert!(check_price_change("v", "i", "n", dec!(10), dec!(11), dec!(0.4), &config).is_none());
+    }
+
+    #[test]
+    fn rejects_negative_margin() {
+        let config = GuardrailConfig::default();
+        let violation =
+            check_price_change("v", "i", "n", dec!(10), dec!(8), dec!(-0.1), &config).unwrap();
+        assert!(violation.reason.contains("negative"));
+    }
+
+    #[test]
+    fn rejects_increase_beyond_max_increase_pct() {
+        let config = GuardrailConfig::default();
+        let violation =
+            check_price_change("v", "i", "n", dec!(10), dec!(13), dec!(0.4), &config).unwrap();
+        assert!(violation.reason.contains("guardrail"));
+    }
+
+    #[test]
+    fn rejects_por_above_ceiling() {
+        let config = GuardrailConfig::default();
+        let violation =
+            check_price_change("v", "i", "n", dec!(10), dec!(10), dec!(0.9), &config).unwrap();
+        assert!(violation.reason.contains("implausible ceiling"));
+    }
+}
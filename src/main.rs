@@ -1,16 +1,26 @@
-use std::{collections::HashMap, time::Duration};
+mod audit;
+mod client;
+mod config;
+mod cost_feed;
+mod guardrail;
 
-use anyhow::Result;
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use chrono::{Duration, Utc};
 use clap::{Parser, Subcommand};
-use http::{HeaderMap, HeaderValue, header::AUTHORIZATION};
-use reqwest::Client;
-use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
-use reqwest_retry::{RetryTransientMiddleware, policies::ExponentialBackoff};
 use rust_decimal::{Decimal, RoundingStrategy, dec, prelude::ToPrimitive};
 use serde::{Deserialize, Serialize};
-use serde_json::{Value, json};
+use serde_json::json;
+use sha2::{Digest, Sha256};
 use tokio::task::JoinSet;
 
+use audit::{AuditLog, Event};
+use client::SquareClient;
+use config::{Config, RoundingBand};
+use cost_feed::{CostFeedRow, CostFeedSource, CsvCostFeed};
+use guardrail::GuardrailViolation;
+
 #[derive(Debug, Parser)]
 struct Args {
     #[clap(subcommand)]
@@ -22,25 +32,101 @@ struct Args {
     #[clap(env)]
     square_app_id: String,
 
+    #[clap(env)]
+    square_app_secret: String,
+
     #[clap(env)]
     square_access_token: String,
+
+    #[clap(env)]
+    square_refresh_token: String,
 }
 
 #[derive(Debug, Clone, Subcommand)]
 enum Command {
     ListZeroMargin,
-    ApplyPriceTargets,
+    ApplyPriceTargets {
+        /// Path to the pricing rules config.
+        #[clap(long, default_value = "pricing.toml")]
+        config: String,
+
+        /// Print the CSV diff of what would change without upserting anything.
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Path to append newline-delimited JSON audit events to.
+        #[clap(long)]
+        audit_log: Option<String>,
+
+        /// Also print audit events to stdout.
+        #[clap(long)]
+        audit_stdout: bool,
+
+        /// Abort the whole run instead of applying it with the guardrail
+        /// violations skipped.
+        #[clap(long)]
+        fail_on_guardrail: bool,
+    },
+    AdjustByVelocity {
+        /// Trailing window, in days, used to measure the observed sales rate.
+        #[clap(long, default_value_t = 14)]
+        window_days: i64,
+
+        /// Path to the pricing rules config.
+        #[clap(long, default_value = "pricing.toml")]
+        config: String,
+
+        /// Print the CSV diff of what would change without upserting anything.
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Path to append newline-delimited JSON audit events to.
+        #[clap(long)]
+        audit_log: Option<String>,
+
+        /// Also print audit events to stdout.
+        #[clap(long)]
+        audit_stdout: bool,
+
+        /// Abort the whole run instead of applying it with the guardrail
+        /// violations skipped.
+        #[clap(long)]
+        fail_on_guardrail: bool,
+    },
+    SyncCosts {
+        /// Path to a CSV cost feed with `sku,upc,unit_cost` columns.
+        #[clap(long)]
+        feed: String,
+
+        /// Print the CSV diff of what would change without upserting anything.
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Path to append newline-delimited JSON audit events to.
+        #[clap(long)]
+        audit_log: Option<String>,
+
+        /// Also print audit events to stdout.
+        #[clap(long)]
+        audit_stdout: bool,
+    },
 }
 
 #[tokio::main]
 pub async fn main() -> Result<()> {
     let _ = dotenvy::dotenv();
     let args = Args::parse();
-    let client = square_client(&args);
+    let client = SquareClient::new(
+        &args.square_app_id,
+        &args.square_app_secret,
+        &args.square_access_token,
+        &args.square_refresh_token,
+    );
 
     match args.command {
         Command::ListZeroMargin => {
-            let rows = get_item_variations(&client)
+            let rows = client
+                .list_catalog::<ItemVariation>("ITEM_VARIATION")
                 .await?
                 .into_iter()
                 .filter(|v| !v.is_deleted)
@@ -62,9 +148,19 @@ pub async fn main() -> Result<()> {
             println!("ItemId,VariationId,Name,UnitPrice,RetailPrice");
             println!("{}", rows);
         }
-        Command::ApplyPriceTargets => {
+        Command::ApplyPriceTargets {
+            config,
+            dry_run,
+            audit_log,
+            audit_stdout,
+            fail_on_guardrail,
+        } => {
+            let config = Config::load(&config).context("loading pricing config")?;
+            let mut audit = AuditLog::new(audit_log.as_deref(), audit_stdout)?;
+
             println!("Fetching catalog...");
-            let variations = get_item_variations(&client)
+            let variations = client
+                .list_catalog::<ItemVariation>("ITEM_VARIATION")
                 .await?
                 .into_iter()
                 .filter(|v| !v.is_deleted)
@@ -95,86 +191,522 @@ pub async fn main() -> Result<()> {
                     acc.extend(val);
                     acc
                 })
-                .unwrap();
+                .unwrap_or_default();
 
-            let updates = variations
-                .iter()
-                .filter_map(|v| {
-                    let mut price =
-                        v.price_data(match item_tax_map.get(&v.data.item_id)?.as_str() {
-                            "2CJE55HCPJY5LHB4ZUEDCRJF" => dec!(0.0),
-                            "3TXZ4AJ4DUCSI6YDBKXHBLRQ" => dec!(0.05),
-                            "QDKOK36EMFC7772L2V64U6YD" => dec!(0.20),
-                            _ => unreachable!(),
-                        })?;
-
-                    if price.por() >= dec!(0.4) {
-                        return None;
-                    }
+            let mut updates = Vec::new();
+            let mut violations = Vec::new();
+            for v in &variations {
+                let Some(tax_id) = item_tax_map.get(&v.data.item_id) else {
+                    audit.record(skipped_event(v, "no tax id found for this variation's item"))?;
+                    continue;
+                };
+                let Some(tax_rate) = config.vat_rate(tax_id) else {
+                    audit.record(skipped_event(v, "unknown tax id"))?;
+                    continue;
+                };
+                let Some(mut price) = v.price_data(tax_rate) else {
+                    audit.record(skipped_event(v, "missing retail price or unit cost"))?;
+                    continue;
+                };
+                let target_por = config.target_por(tax_rate);
+
+                if price.por() >= target_por {
+                    audit.record(skipped_event(v, "already at or above target margin"))?;
+                    continue;
+                }
+
+                let original = price.clone();
+                price.set_por(target_por);
+                price.round_to_retail(&config.rounding);
+
+                let Some(amount) = (price.rrp * dec!(100)).round_dp(2).to_i64() else {
+                    audit.record(skipped_event(v, "new retail price does not fit a Money amount"))?;
+                    continue;
+                };
 
-                    let original = price.clone();
-                    price.set_por(dec!(0.4));
-                    price.round_to_retail();
-
-                    Some((
-                        original.por(),
-                        price.clone().por(),
-                        UpdateItemVariation {
-                            kind: "ITEM_VARIATION".to_string(),
-                            id: v.id.clone(),
-                            data: UpdateItemVariationData {
-                                price_money: Money {
-                                    amount: (price.rrp * dec!(100)).round_dp(2).to_i64()?,
-                                    currency: "GBP".to_string(),
-                                },
+                if let Some(violation) = guardrail::check_price_change(
+                    &v.id,
+                    &v.data.item_id,
+                    &v.data.name,
+                    original.rrp,
+                    price.rrp,
+                    price.por(),
+                    &config.guardrail,
+                ) {
+                    audit.record(skipped_event(v, &violation.reason))?;
+                    violations.push(violation);
+                    continue;
+                }
+
+                if !dry_run {
+                    audit.record(price_changed_event(
+                        v,
+                        &original,
+                        &price,
+                        "apply_price_targets",
+                    ))?;
+                }
+
+                updates.push(PriceUpdate {
+                    variation_id: v.id.clone(),
+                    old_retail: original.rrp,
+                    new_retail: price.rrp,
+                    old_por: original.por(),
+                    new_por: price.clone().por(),
+                    upsert: UpdateItemVariation {
+                        kind: "ITEM_VARIATION".to_string(),
+                        id: v.id.clone(),
+                        version: v.version,
+                        data: UpdateItemVariationData {
+                            price_money: Money {
+                                amount,
+                                currency: "GBP".to_string(),
                             },
                         },
-                    ))
+                    },
+                });
+            }
+
+            if !violations.is_empty() {
+                print_guardrail_report(&violations);
+                if fail_on_guardrail {
+                    anyhow::bail!(
+                        "aborting: {} guardrail violation(s) detected",
+                        violations.len()
+                    );
+                }
+            }
+
+            if dry_run {
+                println!("VariationId,OldRetail,NewRetail,OldPor,NewPor");
+                for u in &updates {
+                    println!(
+                        "{},{},{},{},{}",
+                        u.variation_id, u.old_retail, u.new_retail, u.old_por, u.new_por
+                    );
+                }
+                return Ok(());
+            }
+
+            println!("Updating {} prices", updates.len());
+            let results = batch_upsert_variations(&client, &updates).await?;
+            report_batch_results(&results, &mut audit)?;
+        }
+        Command::AdjustByVelocity {
+            window_days,
+            config,
+            dry_run,
+            audit_log,
+            audit_stdout,
+            fail_on_guardrail,
+        } => {
+            let config = Config::load(&config).context("loading pricing config")?;
+            let mut audit = AuditLog::new(audit_log.as_deref(), audit_stdout)?;
+
+            println!("Fetching catalog...");
+            let variations = client
+                .list_catalog::<ItemVariation>("ITEM_VARIATION")
+                .await?
+                .into_iter()
+                .filter(|v| !v.is_deleted)
+                .filter(|v| config.velocity.variations.contains_key(&v.id))
+                .filter(|v| {
+                    v.data
+                        .default_unit_cost
+                        .as_ref()
+                        .is_some_and(|n| n.amount > 0)
+                })
+                .filter(|v| {
+                    v.data
+                        .price_money
+                        .as_ref()
+                        .is_some_and(|n| n.amount > 0)
                 })
                 .collect::<Vec<_>>();
+            println!(
+                "Found {} variations with configured velocity targets",
+                variations.len()
+            );
+
+            let item_tax_map = variations
+                .chunks(1000)
+                .map(|chunk| get_item_taxes(client.clone(), chunk.to_vec()))
+                .collect::<JoinSet<_>>()
+                .join_all()
+                .await
+                .into_iter()
+                .flatten()
+                .reduce(|mut acc, val| {
+                    acc.extend(val);
+                    acc
+                })
+                .unwrap_or_default();
+
+            let since = Utc::now() - Duration::days(window_days);
+            let variation_ids = variations.iter().map(|v| v.id.clone()).collect::<Vec<_>>();
+            let sales = get_variation_sales_counts(
+                &client,
+                &args.square_location_id,
+                &variation_ids,
+                since,
+            )
+            .await?;
+
+            let mut updates = Vec::new();
+            let mut violations = Vec::new();
+            for v in &variations {
+                let target = &config.velocity.variations[&v.id];
+                let Some(tax_id) = item_tax_map.get(&v.data.item_id) else {
+                    audit.record(skipped_event(v, "no tax id found for this variation's item"))?;
+                    continue;
+                };
+                let Some(tax_rate) = config.vat_rate(tax_id) else {
+                    audit.record(skipped_event(v, "unknown tax id"))?;
+                    continue;
+                };
+                let Some(mut price) = v.price_data(tax_rate) else {
+                    audit.record(skipped_event(v, "missing retail price or unit cost"))?;
+                    continue;
+                };
+                let original = price.clone();
+
+                let used = sales.get(&v.id).copied().unwrap_or(dec!(0));
+                apply_velocity_adjustment(
+                    &mut price,
+                    used,
+                    target,
+                    config.velocity.denominator,
+                    &config.rounding,
+                );
+
+                if price.rrp == original.rrp {
+                    audit.record(skipped_event(v, "velocity adjustment produced no change"))?;
+                    continue;
+                }
+
+                let Some(amount) = (price.rrp * dec!(100)).round_dp(2).to_i64() else {
+                    audit.record(skipped_event(v, "new retail price does not fit a Money amount"))?;
+                    continue;
+                };
+
+                if let Some(violation) = guardrail::check_price_change(
+                    &v.id,
+                    &v.data.item_id,
+                    &v.data.name,
+                    original.rrp,
+                    price.rrp,
+                    price.por(),
+                    &config.guardrail,
+                ) {
+                    audit.record(skipped_event(v, &violation.reason))?;
+                    violations.push(violation);
+                    continue;
+                }
+
+                if !dry_run {
+                    audit.record(price_changed_event(v, &original, &price, "adjust_by_velocity"))?;
+                }
+
+                updates.push(PriceUpdate {
+                    variation_id: v.id.clone(),
+                    old_retail: original.rrp,
+                    new_retail: price.rrp,
+                    old_por: original.por(),
+                    new_por: price.clone().por(),
+                    upsert: UpdateItemVariation {
+                        kind: "ITEM_VARIATION".to_string(),
+                        id: v.id.clone(),
+                        version: v.version,
+                        data: UpdateItemVariationData {
+                            price_money: Money {
+                                amount,
+                                currency: "GBP".to_string(),
+                            },
+                        },
+                    },
+                });
+            }
+
+            if !violations.is_empty() {
+                print_guardrail_report(&violations);
+                if fail_on_guardrail {
+                    anyhow::bail!(
+                        "aborting: {} guardrail violation(s) detected",
+                        violations.len()
+                    );
+                }
+            }
+
+            if dry_run {
+                println!("VariationId,OldRetail,NewRetail,OldPor,NewPor");
+                for u in &updates {
+                    println!(
+                        "{},{},{},{},{}",
+                        u.variation_id, u.old_retail, u.new_retail, u.old_por, u.new_por
+                    );
+                }
+                return Ok(());
+            }
 
-            dbg!(&updates);
             println!("Updating {} prices", updates.len());
-            // curl https://connect.squareup.com/v2/catalog/list?types=ITEM_VARIATION \
-            //   -H 'Square-Version: 2025-10-16' \
-            //   -H 'Authorization: Bearer' \
-            //   -H 'Content-Type: application/json'
-            // -----------------------------------------------------------------------------------------------
-            // curl https://connect.squareup.com/v2/catalog/batch-upsert \
-            //   -X POST \
-            //   -H 'Square-Version: 2025-10-16' \
-            //   -H 'Authorization: Bearer' \
-            //   -H 'Content-Type: application/json' \
-            //   -d '{
-            //     "batches": [
-            //       {
-            //         "objects": [
-            //           {
-            //             "type": "ITEM_VARIATION",
-            //             "item_variation_data": {
-            //               "price_money": {
-            //                 "amount": 123,
-            //                 "currency": "GBP"
-            //               }
-            //             },
-            //             "id": ""
-            //           }
-            //         ]
-            //       }
-            //     ],
-            //     "idempotency_key": "bdf1cfff-aaf7-4b73-82d3-39068a71fcb9"
-            //   }'
+            let results = batch_upsert_variations(&client, &updates).await?;
+            report_batch_results(&results, &mut audit)?;
+        }
+        Command::SyncCosts {
+            feed,
+            dry_run,
+            audit_log,
+            audit_stdout,
+        } => {
+            let mut audit = AuditLog::new(audit_log.as_deref(), audit_stdout)?;
+
+            println!("Reading cost feed...");
+            let rows = CsvCostFeed::new(&feed).rows().await?;
+            println!("Read {} cost feed row(s)", rows.len());
+
+            println!("Fetching catalog...");
+            let variations = client
+                .list_catalog::<ItemVariation>("ITEM_VARIATION")
+                .await?
+                .into_iter()
+                .filter(|v| !v.is_deleted)
+                .collect::<Vec<_>>();
+
+            let by_sku = variations
+                .iter()
+                .filter_map(|v| Some((v.data.sku.clone()?, v)))
+                .collect::<HashMap<_, _>>();
+            let by_upc = variations
+                .iter()
+                .filter_map(|v| Some((v.data.upc.clone()?, v)))
+                .collect::<HashMap<_, _>>();
+
+            let mut updates = Vec::new();
+            let mut unmatched: Vec<CostFeedRow> = Vec::new();
+            let mut matched = 0usize;
+            let mut moved = 0usize;
+
+            for row in &rows {
+                let variation = row
+                    .sku
+                    .as_ref()
+                    .and_then(|sku| by_sku.get(sku))
+                    .or_else(|| row.upc.as_ref().and_then(|upc| by_upc.get(upc)));
+
+                let Some(variation) = variation else {
+                    unmatched.push(row.clone());
+                    continue;
+                };
+                matched += 1;
+
+                let old_cost = variation
+                    .data
+                    .default_unit_cost
+                    .as_ref()
+                    .map(|m| (Decimal::from(m.amount) / dec!(100)).trunc_with_scale(2))
+                    .unwrap_or(dec!(0));
+
+                if old_cost == row.unit_cost {
+                    continue;
+                }
+                moved += 1;
+
+                let Some(amount) = (row.unit_cost * dec!(100)).round_dp(2).to_i64() else {
+                    audit.record(skipped_event(
+                        variation,
+                        "new unit cost does not fit a Money amount",
+                    ))?;
+                    continue;
+                };
+
+                if !dry_run {
+                    audit.record(cost_synced_event(variation, row, old_cost, row.unit_cost))?;
+                }
+
+                updates.push(CostUpdate {
+                    variation_id: variation.id.clone(),
+                    old_cost,
+                    new_cost: row.unit_cost,
+                    upsert: UpdateItemVariationCost {
+                        kind: "ITEM_VARIATION".to_string(),
+                        id: variation.id.clone(),
+                        version: variation.version,
+                        data: UpdateItemVariationCostData {
+                            default_unit_cost: Money {
+                                amount,
+                                currency: "GBP".to_string(),
+                            },
+                        },
+                    },
+                });
+            }
+
+            println!(
+                "{matched} matched, {moved} cost(s) moved, {} unmatched feed row(s)",
+                unmatched.len()
+            );
+            for row in &unmatched {
+                eprintln!("  no catalog match for sku={:?} upc={:?}", row.sku, row.upc);
+            }
+
+            if dry_run {
+                println!("VariationId,OldCost,NewCost");
+                for u in &updates {
+                    println!("{},{},{}", u.variation_id, u.old_cost, u.new_cost);
+                }
+                return Ok(());
+            }
+
+            println!("Updating {} unit costs", updates.len());
+            let results = batch_upsert_cost_updates(&client, &updates).await?;
+            report_batch_results(&results, &mut audit)?;
         }
     }
 
     Ok(())
 }
 
+fn report_batch_results(results: &[BatchResult], audit: &mut AuditLog) -> Result<()> {
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    println!(
+        "{} batches applied, {} failed",
+        results.len() - failed,
+        failed
+    );
+    for result in results {
+        match &result.error {
+            Some(err) => eprintln!(
+                "batch {} ({} variations) failed: {err}",
+                result.idempotency_key, result.variation_count
+            ),
+            None => println!(
+                "batch {} applied {} variations",
+                result.idempotency_key, result.variation_count
+            ),
+        }
+
+        audit.record(Event::BatchUpserted {
+            idempotency_key: result.idempotency_key.clone(),
+            variation_count: result.variation_count,
+            error: result.error.clone(),
+            at: Utc::now(),
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Prints one line per guardrail-rejected price change.
+fn print_guardrail_report(violations: &[GuardrailViolation]) {
+    eprintln!("{} guardrail violation(s):", violations.len());
+    for violation in violations {
+        eprintln!(
+            "  {} ({}, {}): {}",
+            violation.variation_id, violation.item_id, violation.name, violation.reason
+        );
+    }
+}
+
+fn skipped_event(v: &ItemVariation, reason: &str) -> Event {
+    Event::Skipped {
+        variation_id: v.id.clone(),
+        item_id: v.data.item_id.clone(),
+        name: v.data.name.clone(),
+        reason: reason.to_string(),
+        at: Utc::now(),
+    }
+}
+
+fn price_changed_event(
+    v: &ItemVariation,
+    original: &PriceData,
+    price: &PriceData,
+    rule: &str,
+) -> Event {
+    Event::PriceChanged {
+        variation_id: v.id.clone(),
+        item_id: v.data.item_id.clone(),
+        name: v.data.name.clone(),
+        old_retail: original.rrp,
+        new_retail: price.rrp,
+        old_por: original.por(),
+        new_por: price.clone().por(),
+        unit_cost: price.unit,
+        tax_rate: price.tax_rate,
+        rule: rule.to_string(),
+        at: Utc::now(),
+    }
+}
+
+fn cost_synced_event(
+    v: &ItemVariation,
+    row: &CostFeedRow,
+    old_cost: Decimal,
+    new_cost: Decimal,
+) -> Event {
+    Event::CostSynced {
+        variation_id: v.id.clone(),
+        item_id: v.data.item_id.clone(),
+        name: v.data.name.clone(),
+        sku: row.sku.clone(),
+        upc: row.upc.clone(),
+        old_cost,
+        new_cost,
+        at: Utc::now(),
+    }
+}
+
+/// A computed price change for one variation, paired with the upsert payload
+/// that applies it and the before/after figures used for the dry-run diff.
+#[derive(Debug, Clone)]
+struct PriceUpdate {
+    variation_id: String,
+    old_retail: Decimal,
+    new_retail: Decimal,
+    old_por: Decimal,
+    new_por: Decimal,
+    upsert: UpdateItemVariation,
+}
+
+/// Outcome of upserting a single batch of up to 1000 objects.
+#[derive(Debug)]
+struct BatchResult {
+    idempotency_key: String,
+    variation_count: usize,
+    error: Option<String>,
+}
+
+/// A computed `default_unit_cost` change for one variation, paired with the
+/// upsert payload that applies it.
+#[derive(Debug, Clone)]
+struct CostUpdate {
+    variation_id: String,
+    old_cost: Decimal,
+    new_cost: Decimal,
+    upsert: UpdateItemVariationCost,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct UpdateItemVariationCost {
+    #[serde(rename = "type")]
+    kind: String,
+    id: String,
+    version: i64,
+    #[serde(rename = "item_variation_data")]
+    data: UpdateItemVariationCostData,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct UpdateItemVariationCostData {
+    default_unit_cost: Money,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 struct UpdateItemVariation {
     #[serde(rename = "type")]
     kind: String,
     id: String,
+    version: i64,
     #[serde(rename = "item_variation_data")]
     data: UpdateItemVariationData,
 }
@@ -198,6 +730,10 @@ struct ItemVariationData {
     name: String,
     item_id: String,
     pricing_type: String,
+    #[serde(default)]
+    sku: Option<String>,
+    #[serde(default)]
+    upc: Option<String>,
     price_money: Option<Money>,
     default_unit_cost: Option<Money>,
 }
@@ -208,6 +744,21 @@ struct Money {
     currency: String,
 }
 
+/// The catalog `ITEM` object, used to look up which tax applies to a
+/// variation via its parent item's `tax_ids`.
+#[derive(Debug, Clone, Deserialize)]
+struct CatalogItem {
+    id: String,
+    #[serde(rename = "item_data")]
+    data: CatalogItemData,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CatalogItemData {
+    #[serde(default)]
+    tax_ids: Option<Vec<String>>,
+}
+
 #[derive(Debug, Clone)]
 struct PriceData {
     rrp: Decimal,
@@ -257,126 +808,337 @@ impl PriceData {
         self.rrp = gross.round_dp_with_strategy(2, RoundingStrategy::ToZero);
     }
 
-    pub fn round_to_retail(&mut self) {
+    /// Snaps `self.rrp` to the first configured `RoundingBand` whose `upto`
+    /// covers the price's last pence digit, falling back to the last band if
+    /// none match.
+    pub fn round_to_retail(&mut self, bands: &[RoundingBand]) {
         let pennies = (self.rrp * dec!(100)).round_dp(0);
         let Some(mut pennies) = pennies.to_i64() else {
             return;
         };
+        let Some(last_band) = bands.last() else {
+            return;
+        };
 
         let sign = if pennies < 0 { -1 } else { 1 };
-        let last_digit = (pennies.abs() % 10) as i64;
-        let target = if last_digit <= 2 {
-            0
-        } else if last_digit <= 5 {
-            5
-        } else {
-            9
-        };
-        pennies += sign * (target - last_digit);
+        let last_digit = (pennies.abs() % 10) as u8;
+        let ending = bands
+            .iter()
+            .find(|band| last_digit <= band.upto)
+            .unwrap_or(last_band)
+            .ending as i64;
+
+        pennies += sign * (ending - last_digit as i64);
 
         self.rrp = Decimal::new(pennies, 2);
     }
 }
 
+/// Applies the EIP-1559-style base-fee recurrence to `price.rrp`: fast
+/// sellers (`used > target`) drift retail up toward a wider margin, slow
+/// movers drift down, then the result is clamped to the configured POR band
+/// and snapped to a retail-friendly ending.
+fn apply_velocity_adjustment(
+    price: &mut PriceData,
+    used: Decimal,
+    target: &config::VelocityTarget,
+    denominator: Decimal,
+    rounding: &[RoundingBand],
+) {
+    if target.target == dec!(0) {
+        return;
+    }
+
+    let delta = ((used - target.target) / target.target) / denominator;
+    price.rrp = (price.rrp * (dec!(1) + delta)).round_dp(2);
+
+    let implied_por = price.por();
+    if implied_por < target.min_por {
+        price.set_por(target.min_por);
+    } else if implied_por > target.max_por {
+        price.set_por(target.max_por);
+    }
+
+    price.round_to_retail(rounding);
+}
+
+/// Tallies unit sales per catalog variation ID from completed orders closed
+/// since `since`, paginating through `/v2/orders/search`.
+async fn get_variation_sales_counts(
+    client: &SquareClient,
+    location_id: &str,
+    variation_ids: &[String],
+    since: chrono::DateTime<Utc>,
+) -> Result<HashMap<String, Decimal>> {
+    let mut cursor: Option<String> = None;
+    let mut counts: HashMap<String, Decimal> = HashMap::new();
+
+    loop {
+        let mut body = json!({
+            "location_ids": [location_id],
+            "limit": 500,
+            "query": {
+                "filter": {
+                    "date_time_filter": {
+                        "closed_at": { "start_at": since.to_rfc3339() }
+                    },
+                    "state_filter": { "states": ["COMPLETED"] }
+                }
+            },
+        });
+        if let Some(cursor) = &cursor {
+            body["cursor"] = json!(cursor);
+        }
+
+        let mut data: serde_json::Value = client.post("/v2/orders/search", &body).await?;
+
+        for order in data
+            .get("orders")
+            .and_then(|orders| orders.as_array())
+            .into_iter()
+            .flatten()
+        {
+            for line_item in order
+                .get("line_items")
+                .and_then(|items| items.as_array())
+                .into_iter()
+                .flatten()
+            {
+                let Some(id) = line_item.get("catalog_object_id").and_then(|v| v.as_str()) else {
+                    continue;
+                };
+                if !variation_ids.iter().any(|v| v == id) {
+                    continue;
+                }
+
+                let quantity = line_item
+                    .get("quantity")
+                    .and_then(|q| q.as_str())
+                    .and_then(|q| q.parse::<Decimal>().ok())
+                    .unwrap_or(dec!(1));
+
+                *counts.entry(id.to_string()).or_insert(dec!(0)) += quantity;
+            }
+        }
+
+        cursor = data
+            .get_mut("cursor")
+            .and_then(|c| c.as_str().map(str::to_string));
+        if cursor.is_none() {
+            break;
+        }
+    }
+
+    Ok(counts)
+}
+
 async fn get_item_taxes(
-    client: ClientWithMiddleware,
+    client: SquareClient,
     variations: Vec<ItemVariation>,
 ) -> Result<HashMap<String, String>> {
-    Ok(client
-        .post("https://connect.squareup.com/v2/catalog/batch-retrieve")
-        .json(&json!({
-            "object_ids": variations.iter().map(|v| v.data.item_id.clone()).collect::<Vec<_>>(),
-            "include_category_path_to_root": false,
-            "include_related_objects": false
-        }))
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<Value>()
-        .await?
-        .get("objects")
-        .and_then(|objects| {
-            Some(
-                objects
-                    .as_array()
-                    .expect("as_array")
-                    .into_iter()
-                    .filter_map(|obj| {
-                        Some((
-                            obj.get("id").expect("id").as_str()?.to_string(),
-                            obj.get("item_data")
-                                .expect("item_data")
-                                .get("tax_ids")?
-                                .as_array()
-                                .expect("as_array")
-                                .get(0)
-                                .expect("get(0)")
-                                .as_str()?
-                                .to_string(),
-                        ))
-                    })
-                    .collect::<HashMap<_, _>>(),
-            )
+    let item_ids = variations
+        .iter()
+        .map(|v| v.data.item_id.clone())
+        .collect::<Vec<_>>();
+
+    let items: Vec<CatalogItem> = client.batch_retrieve(&item_ids).await?;
+
+    Ok(items
+        .into_iter()
+        .filter_map(|item| {
+            let tax_id = item.data.tax_ids?.into_iter().next()?;
+            Some((item.id, tax_id))
         })
-        .unwrap_or_default())
+        .collect())
 }
 
-async fn get_item_variations(client: &ClientWithMiddleware) -> Result<Vec<ItemVariation>> {
-    let mut cursor = Some(String::new());
-    let mut result = Vec::new();
+/// POSTs `updates` to `/v2/catalog/batch-upsert` in batches of up to 1000
+/// objects (Square's per-batch limit), returning one [`BatchResult`] per
+/// batch so callers can surface partial failures instead of aborting the
+/// whole run.
+async fn batch_upsert_variations(
+    client: &SquareClient,
+    updates: &[PriceUpdate],
+) -> Result<Vec<BatchResult>> {
+    let mut results = Vec::new();
 
-    while let Some(current_cursor) = cursor.as_deref() {
-        let mut query = vec![("types", "ITEM_VARIATION")];
-        if !current_cursor.is_empty() {
-            query.push(("cursor", current_cursor));
-        }
+    for batch in updates.chunks(1000) {
+        let idempotency_key = batch_idempotency_key(batch);
+        let objects = batch.iter().map(|u| &u.upsert).collect::<Vec<_>>();
+        let outcome = client.batch_upsert(&idempotency_key, &objects).await;
 
-        match client
-            .get("https://connect.squareup.com/v2/catalog/list")
-            .query(&query)
-            .send()
-            .await?
-            .error_for_status()
-        {
-            Ok(res) => {
-                let mut data: Value = res.json().await?;
-                cursor = data.get("cursor").and_then(|c| c.as_str().map(Into::into));
-                result.extend(match data.get_mut("objects") {
-                    Some(value) => serde_json::from_value(value.take())?,
-                    None => Vec::new(),
-                });
-            }
-            Err(err) => {
-                eprintln!("{err}");
-            }
-        };
+        results.push(BatchResult {
+            idempotency_key,
+            variation_count: batch.len(),
+            error: outcome.err().map(|err| err.to_string()),
+        });
+    }
+
+    Ok(results)
+}
+
+/// Derives a stable idempotency key from the variation IDs and target prices
+/// in a batch, so replaying the same batch after a crash produces the same
+/// key and Square de-dupes the retry instead of double-applying it.
+fn batch_idempotency_key(batch: &[PriceUpdate]) -> String {
+    let mut hasher = Sha256::new();
+    for update in batch {
+        hasher.update(update.variation_id.as_bytes());
+        hasher.update(update.upsert.data.price_money.amount.to_le_bytes());
     }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// POSTs `updates` to `/v2/catalog/batch-upsert` in batches of up to 1000
+/// objects, mirroring [`batch_upsert_variations`] but for `default_unit_cost`
+/// changes from [`Command::SyncCosts`].
+async fn batch_upsert_cost_updates(
+    client: &SquareClient,
+    updates: &[CostUpdate],
+) -> Result<Vec<BatchResult>> {
+    let mut results = Vec::new();
+
+    for batch in updates.chunks(1000) {
+        let idempotency_key = cost_batch_idempotency_key(batch);
+        let objects = batch.iter().map(|u| &u.upsert).collect::<Vec<_>>();
+        let outcome = client.batch_upsert(&idempotency_key, &objects).await;
+
+        results.push(BatchResult {
+            idempotency_key,
+            variation_count: batch.len(),
+            error: outcome.err().map(|err| err.to_string()),
+        });
+    }
+
+    Ok(results)
+}
 
-    Ok(result)
+/// Derives a stable idempotency key from the variation IDs and new costs in
+/// a batch, so replaying the same batch after a crash de-dupes instead of
+/// double-applying it.
+fn cost_batch_idempotency_key(batch: &[CostUpdate]) -> String {
+    let mut hasher = Sha256::new();
+    for update in batch {
+        hasher.update(update.variation_id.as_bytes());
+        hasher.update(update.upsert.data.default_unit_cost.amount.to_le_bytes());
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
 }
 
-fn square_client(args: &Args) -> ClientWithMiddleware {
-    let mut headers = HeaderMap::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price_update(variation_id: &str, amount: i64) -> PriceUpdate {
+        PriceUpdate {
+            variation_id: variation_id.to_string(),
+            old_retail: dec!(0),
+            new_retail: dec!(0),
+            old_por: dec!(0),
+            new_por: dec!(0),
+            upsert: UpdateItemVariation {
+                kind: "ITEM_VARIATION".to_string(),
+                id: variation_id.to_string(),
+                version: 1,
+                data: UpdateItemVariationData {
+                    price_money: Money {
+                        amount,
+                        currency: "GBP".to_string(),
+                    },
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn batch_idempotency_key_is_deterministic() {
+        let batch = vec![price_update("a", 100), price_update("b", 200)];
+        assert_eq!(batch_idempotency_key(&batch), batch_idempotency_key(&batch));
+    }
+
+    #[test]
+    fn batch_idempotency_key_changes_with_price() {
+        let a = vec![price_update("a", 100)];
+        let b = vec![price_update("a", 101)];
+        assert_ne!(batch_idempotency_key(&a), batch_idempotency_key(&b));
+    }
+
+    #[test]
+    fn round_to_retail_snaps_to_configured_band() {
+        let bands = vec![
+            RoundingBand { upto: 2, ending: 0 },
+            RoundingBand { upto: 5, ending: 5 },
+            RoundingBand { upto: 9, ending: 9 },
+        ];
 
-    let mut auth_value =
-        HeaderValue::from_str(&format!("Bearer {}", args.square_access_token)).unwrap();
-    auth_value.set_sensitive(true);
-    headers.insert(AUTHORIZATION, auth_value);
+        let mut price = PriceData {
+            rrp: dec!(9.83),
+            unit: dec!(0),
+            tax_rate: dec!(0),
+        };
+        price.round_to_retail(&bands);
+        assert_eq!(price.rrp, dec!(9.85));
 
-    headers.insert("Square-Version", "2025-10-16".parse().unwrap());
-    headers.insert("Content-Type", "application/json".parse().unwrap());
+        let mut price = PriceData {
+            rrp: dec!(9.80),
+            unit: dec!(0),
+            tax_rate: dec!(0),
+        };
+        price.round_to_retail(&bands);
+        assert_eq!(price.rrp, dec!(9.80));
+    }
+
+    fn velocity_target(target: Decimal) -> config::VelocityTarget {
+        config::VelocityTarget {
+            target,
+            min_por: dec!(0),
+            max_por: dec!(2),
+        }
+    }
 
-    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(3);
+    #[test]
+    fn velocity_adjustment_drifts_price_up_for_fast_sellers() {
+        let bands = vec![RoundingBand { upto: 9, ending: 5 }];
+        let mut price = PriceData {
+            rrp: dec!(10),
+            unit: dec!(0),
+            tax_rate: dec!(0),
+        };
+        apply_velocity_adjustment(&mut price, dec!(20), &velocity_target(dec!(10)), dec!(8), &bands);
+        assert_eq!(price.rrp, dec!(11.25));
+    }
 
-    let client = Client::builder()
-        .timeout(Duration::from_secs(30))
-        .default_headers(headers)
-        .build()
-        .unwrap();
+    #[test]
+    fn velocity_adjustment_drifts_price_down_for_slow_movers() {
+        let bands = vec![RoundingBand { upto: 9, ending: 5 }];
+        let mut price = PriceData {
+            rrp: dec!(10),
+            unit: dec!(0),
+            tax_rate: dec!(0),
+        };
+        apply_velocity_adjustment(&mut price, dec!(0), &velocity_target(dec!(10)), dec!(8), &bands);
+        assert_eq!(price.rrp, dec!(8.75));
+    }
 
-    ClientBuilder::new(client)
-        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-        .build()
+    #[test]
+    fn velocity_adjustment_is_a_no_op_when_target_is_zero() {
+        let bands = vec![RoundingBand { upto: 9, ending: 5 }];
+        let mut price = PriceData {
+            rrp: dec!(10),
+            unit: dec!(0),
+            tax_rate: dec!(0),
+        };
+        apply_velocity_adjustment(&mut price, dec!(20), &velocity_target(dec!(0)), dec!(8), &bands);
+        assert_eq!(price.rrp, dec!(10));
+    }
 }
 
 // {